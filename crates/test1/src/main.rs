@@ -1,26 +1,248 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::f32::consts::PI;
-use std::ops::{Add, Sub};
+use std::fs;
+use std::path::PathBuf;
 
-use bevy::input::mouse::{MouseButtonInput, MouseMotion};
+use bevy::diagnostic::{DiagnosticsPlugin, Diagnostics, FrameTimeDiagnosticsPlugin};
+use bevy::input::mouse::MouseButtonInput;
+use bevy::input::{ElementState, InputPlugin};
 use bevy::prelude::*;
+use bevy::render::camera::Camera;
+use bevy::window::WindowPlugin;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if let Some(index) = args.iter().position(|arg| arg == "--headless") {
+        let ticks = args.get(index + 1).and_then(|ticks| ticks.parse().ok()).unwrap_or(600);
+        let seed = args.windows(2).find(|pair| pair[0] == "--seed").and_then(|pair| pair[1].parse().ok()).unwrap_or(0);
+        let hold_fire = args.iter().any(|arg| arg == "--fire");
+
+        let report = run_headless(ticks, seed, Mutators::from_args(&args), Loadout::from_args(&args), load_weapon_database(), |tick, app| {
+            if hold_fire && tick == 0 {
+                app.resources.get_mut::<Events<MouseButtonInput>>().unwrap().send(MouseButtonInput { button: MouseButton::Left, state: ElementState::Pressed });
+            }
+        });
+
+        println!("ticks: {}, entities: {}, projectiles: {}, player position: {:?}", report.ticks, report.entities, report.projectiles, report.player_position);
+        return;
+    }
+
     App::build()
         .add_plugins(DefaultPlugins)
+        .add_resource(Mutators::from_args(&args))
+        .add_resource(Loadout::from_args(&args))
+        .add_resource(load_weapon_database())
+        .add_plugin(GamePlugin)
         .add_startup_system(setup.system())
-        .add_system(input_system.system())
-        .add_system(velocity_system.system())
-        .add_system(friction_system.system())
-        .add_system(mouse_system.system())
-        .add_system(fire_system.system())
-        .add_system(kill_system.system())
         .run();
 }
 
+struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        if !app.resources().contains::<WeaponDatabase>() {
+            app.init_resource::<WeaponDatabase>();
+        }
+
+        if !app.resources().contains::<Loadout>() {
+            app.init_resource::<Loadout>();
+        }
+
+        if !app.resources().contains::<Mutators>() {
+            app.init_resource::<Mutators>();
+        }
+
+        if !app.resources().contains::<GameRng>() {
+            app.init_resource::<GameRng>();
+        }
+
+        app.add_plugin(FrameTimeDiagnosticsPlugin)
+            .add_resource(DeltaClamp { max_delta: 0.1, elapsed: 0.0 })
+            .add_resource(InputConfig { keyboard_aim: false, keyboard_turn_rate: 2.0 * PI })
+            .add_resource(MovementConfig { max_speed: 500.0, responsiveness: 0.3, velocity_retention: 0.7 })
+            .add_resource(FireMode { auto: true })
+            .add_resource(InputBuffer { fire_pressed_at: None, window: 0.15 })
+            .add_resource(FacingMode::Rotate)
+            .add_resource(AimMode::Free)
+            .add_resource(AimOrigin::Muzzle)
+            .add_resource(AimSmoothing { enabled: false, turn_rate: 4.0 * PI })
+            .add_resource(ProjectileCap { max_live: 200 })
+            .add_resource(PerfGovernor { degrade_above: 1.0 / 30.0, recover_below: 1.0 / 50.0, adjust_rate: 0.5, min_scale: 0.25, scale: 1.0 })
+            .add_resource(DespawnQueue::default())
+            .add_startup_system(apply_mutators_system.system())
+            .add_system_to_stage(stage::PRE_UPDATE, delta_clamp_system.system())
+            .add_system(input_system.system())
+            .add_system(velocity_system.system())
+            .add_system(face_velocity_system.system())
+            .add_system(friction_system.system())
+            .add_system(facing_mode_system.system())
+            .add_system(aim_mode_system.system())
+            .add_system(aim_origin_system.system())
+            .add_system(aim_smoothing_mode_system.system())
+            .add_system(keyboard_aim_mode_system.system())
+            .add_system(mouse_system.system())
+            .add_system(keyboard_aim_system.system())
+            .add_system(aim_system.system())
+            .add_system(fire_mode_system.system())
+            .add_system(fire_system.system())
+            .add_system(heat_system.system())
+            .add_system(bloom_system.system())
+            .add_system(kill_system.system())
+            .add_system(range_system.system())
+            .add_system(perf_governor_system.system())
+            .add_system(projectile_cap_system.system())
+            .add_system_to_stage(stage::POST_UPDATE, despawn_system.system());
+    }
+}
+
+struct SimulationReport {
+    ticks: u32,
+    entities: usize,
+    projectiles: usize,
+    player_position: Vec2,
+}
+
+fn run_headless(ticks: u32, seed: u64, mutators: Mutators, loadout: Loadout, weapon_database: WeaponDatabase, mut script: impl FnMut(u32, &mut App)) -> SimulationReport {
+    let tick_seconds = 1.0 / 60.0;
+
+    let mut builder = App::build();
+
+    builder
+        .add_plugin(DiagnosticsPlugin)
+        .add_plugin(InputPlugin)
+        .add_plugin(WindowPlugin { add_primary_window: false, exit_on_close: false })
+        .add_resource(Time::default())
+        .add_resource(GameRng(StdRng::seed_from_u64(seed)))
+        .add_resource(mutators)
+        .add_resource(loadout)
+        .add_resource(weapon_database)
+        .add_plugin(GamePlugin)
+        .add_startup_system(headless_setup.system());
+
+    let mut app = std::mem::take(&mut builder.app);
+
+    app.initialize();
+    app.executor.initialize(&mut app.resources);
+
+    for tick in 0..ticks {
+        {
+            let mut time = app.resources.get_mut::<Time>().unwrap();
+            time.delta_seconds = tick_seconds as f32;
+            time.delta_seconds_f64 = tick_seconds;
+            time.seconds_since_startup += tick_seconds;
+        }
+
+        script(tick, &mut app);
+        app.update();
+    }
+
+    SimulationReport {
+        ticks,
+        entities: app.world.iter().count(),
+        projectiles: app.world.query::<&Projectile>().count(),
+        player_position: app.world.query::<(&Shooter, &Transform)>().map(|(_, transform)| transform.translation.truncate()).next().unwrap_or_default(),
+    }
+}
+
 struct Lifespan {
     kill_at: f64,
 }
 
+struct RangeLimit {
+    max_distance: f32,
+    start: Vec3,
+}
+
+#[derive(Clone, Deserialize)]
+struct Splitting {
+    count: u32,
+    spread: f32,
+    lifespan: f64,
+}
+
+#[derive(Clone, Deserialize)]
+struct HeatConfig {
+    max: f32,
+    per_shot: f32,
+    cooldown_rate: f32,
+    reset_below: f32,
+}
+
+struct Heat {
+    config: HeatConfig,
+    current: f32,
+    locked: bool,
+}
+
+#[derive(Clone, Deserialize)]
+struct BloomConfig {
+    max: f32,
+    per_shot: f32,
+    recovery: f32,
+}
+
+struct Bloom {
+    config: BloomConfig,
+    current: f32,
+}
+
+struct FaceVelocity;
+
+struct Projectile {
+    id: u64,
+}
+
+struct ProjectileCap {
+    max_live: usize,
+}
+
+struct PerfGovernor {
+    degrade_above: f64,
+    recover_below: f64,
+    adjust_rate: f32,
+    min_scale: f32,
+    scale: f32,
+}
+
+impl PerfGovernor {
+    fn observe(&mut self, frame_time: f64, delta: f32) {
+        if frame_time > self.degrade_above {
+            self.scale = (self.scale - self.adjust_rate * delta).max(self.min_scale);
+        } else if frame_time < self.recover_below {
+            self.scale = (self.scale + self.adjust_rate * delta).min(1.0);
+        }
+    }
+}
+
+#[derive(Default)]
+struct DespawnQueue {
+    pending: HashSet<Entity>,
+}
+
+impl DespawnQueue {
+    fn push(&mut self, entity: Entity) -> bool {
+        self.pending.insert(entity)
+    }
+
+    fn contains(&self, entity: Entity) -> bool {
+        self.pending.contains(&entity)
+    }
+}
+
+struct GameRng(StdRng);
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng(StdRng::from_entropy())
+    }
+}
+
 struct Velocity {
     magnitude: Vec3,
     last_change: f64,
@@ -29,125 +251,1371 @@ struct Velocity {
 
 struct Shooter {
     pew_handle: Handle<ColorMaterial>,
+    weapon: String,
+    muzzle_offset: Vec2,
     shoot_direction: Vec2,
     shoot_angle: f32,
+    aim_angle: f32,
+    smoothed_angle: f32,
     last_shot_at: f64,
 }
 
-fn setup(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>, asset_server: Res<AssetServer>) {
+struct DeltaClamp {
+    max_delta: f32,
+    elapsed: f64,
+}
+
+impl DeltaClamp {
+    fn delta(&self, time: &Time) -> f32 {
+        time.delta_seconds.min(self.max_delta)
+    }
+}
+
+struct InputConfig {
+    keyboard_aim: bool,
+    keyboard_turn_rate: f32,
+}
+
+struct MovementConfig {
+    max_speed: f32,
+    responsiveness: f32,
+    velocity_retention: f32,
+}
+
+#[derive(Default)]
+struct Mutators {
+    ice_physics: bool,
+}
+
+impl Mutators {
+    fn from_args(args: &[String]) -> Self {
+        let mut mutators = Mutators::default();
+
+        for pair in args.windows(2).filter(|pair| pair[0] == "--mutator") {
+            match pair[1].as_str() {
+                "ice-physics" => mutators.ice_physics = true,
+                unknown => eprintln!("unknown mutator {}", unknown),
+            }
+        }
+
+        mutators
+    }
+}
+
+struct Loadout {
+    weapon: String,
+}
+
+impl Default for Loadout {
+    fn default() -> Self {
+        Loadout { weapon: "pistol".to_string() }
+    }
+}
+
+impl Loadout {
+    fn from_args(args: &[String]) -> Self {
+        args.windows(2)
+            .find(|pair| pair[0] == "--weapon")
+            .map(|pair| Loadout { weapon: pair[1].clone() })
+            .unwrap_or_default()
+    }
+}
+
+struct FireMode {
+    auto: bool,
+}
+
+struct InputBuffer {
+    fire_pressed_at: Option<f64>,
+    window: f64,
+}
+
+enum FacingMode {
+    Rotate,
+    Flip,
+}
+
+enum AimMode {
+    Free,
+    EightWay,
+}
+
+enum AimOrigin {
+    Center,
+    Muzzle,
+}
+
+struct AimSmoothing {
+    enabled: bool,
+    turn_rate: f32,
+}
+
+#[derive(Deserialize)]
+struct WeaponStats {
+    cooldown: f64,
+    projectile_speed: f32,
+    spread: f32,
+    pellets: u32,
+    #[serde(default)]
+    lifespan: Option<f64>,
+    #[serde(default)]
+    range: Option<f32>,
+    #[serde(default)]
+    split: Option<Splitting>,
+    #[serde(default)]
+    heat: Option<HeatConfig>,
+    #[serde(default)]
+    bloom: Option<BloomConfig>,
+    #[serde(default = "default_projectile_scale")]
+    scale: f32,
+    #[serde(default)]
+    symmetric: bool,
+    #[serde(default)]
+    inherit_velocity: f32,
+}
+
+fn default_projectile_scale() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+struct WeaponDatabase {
+    weapons: HashMap<String, WeaponStats>,
+}
+
+impl Default for WeaponDatabase {
+    fn default() -> Self {
+        let mut weapons = HashMap::new();
+
+        weapons.insert("pistol".to_string(), WeaponStats { cooldown: 0.1, projectile_speed: 2000.0, spread: 0.0, pellets: 1, lifespan: Some(0.5), range: None, split: None, heat: None, bloom: None, scale: 1.0, symmetric: false, inherit_velocity: 0.0 });
+
+        WeaponDatabase { weapons }
+    }
+}
+
+impl WeaponDatabase {
+    fn resolve(&self, weapon: &str) -> String {
+        if self.weapons.contains_key(weapon) {
+            return weapon.to_string();
+        }
+
+        let default = Loadout::default().weapon;
+        let fallback = if self.weapons.contains_key(&default) { default } else { self.weapons.keys().min().cloned().unwrap_or(default) };
+
+        eprintln!("unknown weapon {}, using {}", weapon, fallback);
+        fallback
+    }
+}
+
+fn asset_path(file: &str) -> PathBuf {
+    let root = env::var("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .or_else(|_| env::current_exe().map(|exe| exe.parent().map(PathBuf::from).unwrap_or_default()))
+        .unwrap_or_default();
+
+    root.join("assets").join(file)
+}
+
+fn parse_weapon_database(source: &str) -> Result<WeaponDatabase, String> {
+    let database: WeaponDatabase = ron::de::from_str(source).map_err(|e| e.to_string())?;
+
+    if database.weapons.is_empty() {
+        return Err("no weapons defined".to_string());
+    }
+
+    for (name, stats) in &database.weapons {
+        if stats.lifespan.is_none() && stats.range.is_none() {
+            return Err(format!("weapon {} needs a lifespan or a range", name));
+        }
+    }
+
+    Ok(database)
+}
+
+fn load_weapon_database() -> WeaponDatabase {
+    let path = asset_path("weapons.ron");
+
+    let parsed = fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|source| parse_weapon_database(&source));
+
+    match parsed {
+        Ok(database) => database,
+        Err(e) => {
+            eprintln!("failed to load {}, using built-in weapons: {}", path.display(), e);
+            WeaponDatabase::default()
+        }
+    }
+}
+
+fn setup(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>, asset_server: Res<AssetServer>, loadout: Res<Loadout>, weapon_database: Res<WeaponDatabase>) {
     let texture = asset_server.load("dude.png");
     let pew = asset_server.load("pew.png");
 
+    commands.spawn(Camera2dComponents::default());
+
+    spawn_player(&mut commands, materials.add(texture.into()), materials.add(pew.into()), &loadout, &weapon_database);
+}
+
+fn headless_setup(mut commands: Commands, loadout: Res<Loadout>, weapon_database: Res<WeaponDatabase>) {
+    commands.spawn(Camera2dComponents::default());
+
+    spawn_player(&mut commands, Handle::default(), Handle::default(), &loadout, &weapon_database);
+}
+
+fn spawn_player(commands: &mut Commands, material: Handle<ColorMaterial>, pew_handle: Handle<ColorMaterial>, loadout: &Loadout, weapon_database: &WeaponDatabase) {
+    let weapon = weapon_database.resolve(&loadout.weapon);
+    let stats = weapon_database.weapons.get(&weapon);
+    let heat = stats.and_then(|stats| stats.heat.clone());
+    let bloom = stats.and_then(|stats| stats.bloom.clone());
+
     commands
-        .spawn(Camera2dComponents::default())
         .spawn(SpriteComponents {
-            material: materials.add(texture.into()),
+            material,
             transform: Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
             ..Default::default()
         })
         .with(Velocity { magnitude: Default::default(), last_change: 0.0, no_friction: false })
         .with(Shooter {
-            pew_handle: materials.add(pew.into()),
+            pew_handle,
+            weapon,
+            muzzle_offset: Vec2::new(50.0, -8.0),
             shoot_direction: Default::default(),
             shoot_angle: 0.0,
+            aim_angle: 0.0,
+            smoothed_angle: 0.0,
             last_shot_at: 0.0,
         });
+
+    if let Some(config) = heat {
+        commands.with(Heat { config, current: 0.0, locked: false });
+    }
+
+    if let Some(config) = bloom {
+        commands.with(Bloom { config, current: 0.0 });
+    }
 }
 
-fn mouse_system(mut state: Local<EventReader<CursorMoved>>, events: Res<Events<CursorMoved>>, mut query: Query<(&mut Transform, &Velocity, &mut Shooter)>) {
-    for event in state.iter(&events) {
-        for (mut t, _, mut shooter) in query.iter_mut() {
-            let view_dir_vec: Vec2 = (event.position - Vec2::new(1280.0 / 2.0, 400.0)) - Vec2::new(t.translation.x(), t.translation.y());
-            let angle = view_dir_vec.angle_between(Vec2::new(1.0, 0.0));
+fn cursor_to_world(window: &Window, camera_transform: &Transform, cursor_pos: Vec2) -> Vec2 {
+    let window_size = Vec2::new(window.width() as f32, window.height() as f32);
+    let offset = (cursor_pos - window_size / 2.0) * camera_transform.scale.truncate();
 
-            t.rotation = Quat::from_rotation_z(-angle - PI / 2.0);
-            shooter.shoot_direction = view_dir_vec;
-            shooter.shoot_angle = -angle;
-        }
+    camera_transform.translation.truncate() + offset
+}
+
+fn facing_mode_system(keyboard_input: Res<Input<KeyCode>>, mut facing_mode: ResMut<FacingMode>) {
+    if keyboard_input.just_pressed(KeyCode::F) {
+        *facing_mode = match *facing_mode {
+            FacingMode::Rotate => FacingMode::Flip,
+            FacingMode::Flip => FacingMode::Rotate,
+        };
     }
 }
 
-fn kill_system(mut commands: Commands, time: Res<Time>, query: Query<(Entity, &Lifespan)>) {
-    for (entity, lifespan) in query.iter() {
-        if time.seconds_since_startup >= lifespan.kill_at {
-            commands.despawn(entity);
-        }
+fn aim_mode_system(keyboard_input: Res<Input<KeyCode>>, mut aim_mode: ResMut<AimMode>) {
+    if keyboard_input.just_pressed(KeyCode::G) {
+        *aim_mode = match *aim_mode {
+            AimMode::Free => AimMode::EightWay,
+            AimMode::EightWay => AimMode::Free,
+        };
+    }
+}
+
+fn snap_angle(angle: f32, aim_mode: &AimMode) -> f32 {
+    match aim_mode {
+        AimMode::Free => angle,
+        AimMode::EightWay => (angle / (PI / 4.0)).round() * (PI / 4.0),
+    }
+}
+
+fn aim_origin_system(keyboard_input: Res<Input<KeyCode>>, mut aim_origin: ResMut<AimOrigin>) {
+    if keyboard_input.just_pressed(KeyCode::M) {
+        *aim_origin = match *aim_origin {
+            AimOrigin::Center => AimOrigin::Muzzle,
+            AimOrigin::Muzzle => AimOrigin::Center,
+        };
+    }
+}
+
+fn muzzle_position(origin: Vec3, angle: f32, muzzle_offset: Vec2, aim_origin: &AimOrigin) -> Vec3 {
+    let forward = Vec3::new(angle.cos(), angle.sin(), 0.0);
+    let left = Vec3::new(-angle.sin(), angle.cos(), 0.0);
+
+    match aim_origin {
+        AimOrigin::Center => origin + forward * muzzle_offset.x(),
+        AimOrigin::Muzzle => origin + forward * muzzle_offset.x() + left * muzzle_offset.y(),
+    }
+}
+
+fn aim_angle_towards(origin: Vec2, target: Vec2, muzzle_offset: Vec2, aim_origin: &AimOrigin) -> f32 {
+    let to_target = target - origin;
+    let center_angle = to_target.y().atan2(to_target.x());
+    let behind_muzzle = to_target.length() <= muzzle_offset.length();
+
+    match aim_origin {
+        AimOrigin::Muzzle if !behind_muzzle => center_angle - (muzzle_offset.y() / to_target.length()).asin(),
+        _ => center_angle,
+    }
+}
+
+fn aim_smoothing_mode_system(keyboard_input: Res<Input<KeyCode>>, mut aim_smoothing: ResMut<AimSmoothing>) {
+    if keyboard_input.just_pressed(KeyCode::H) {
+        aim_smoothing.enabled = !aim_smoothing.enabled;
+    }
+}
+
+fn turn_towards(from: f32, to: f32, max_step: f32) -> f32 {
+    let mut diff = (to - from) % (2.0 * PI);
+
+    if diff > PI {
+        diff -= 2.0 * PI;
+    } else if diff < -PI {
+        diff += 2.0 * PI;
+    }
+
+    if diff.abs() <= max_step {
+        to
+    } else {
+        from + max_step * diff.signum()
+    }
+}
+
+fn keyboard_aim_mode_system(keyboard_input: Res<Input<KeyCode>>, mut input_config: ResMut<InputConfig>) {
+    if keyboard_input.just_pressed(KeyCode::K) {
+        input_config.keyboard_aim = !input_config.keyboard_aim;
     }
 }
 
-fn fire_system(mut commands: Commands, time: Res<Time>, mut state: Local<EventReader<MouseButtonInput>>, events: Res<Events<MouseButtonInput>>, mut query: Query<(&mut Transform, &mut Shooter)>) {
+fn mouse_system(mut state: Local<EventReader<CursorMoved>>, events: Res<Events<CursorMoved>>, windows: Res<Windows>, input_config: Res<InputConfig>, aim_origin: Res<AimOrigin>, cameras: Query<(&Camera, &Transform)>, mut query: Query<(&Transform, &mut Shooter)>) {
     for event in state.iter(&events) {
-        if event.button == MouseButton::Left {
-            for (mut t, mut shooter) in query.iter_mut() {
-                if time.seconds_since_startup - shooter.last_shot_at > 0.1 {
-                    shooter.last_shot_at = time.seconds_since_startup;
-
-                    let mut transform = Transform::from_rotation(Quat::from_rotation_z(shooter.shoot_angle));
-                    let dir = Vec3::new(shooter.shoot_direction.x(), shooter.shoot_direction.y(), 0.0);
-
-                    transform.translation = t.translation.clone() + dir.normalize() * 50.0;
-
-                    commands.spawn(SpriteComponents {
-                        material: shooter.pew_handle.clone(),
-                        transform,
-                        ..Default::default()
-                    })
-                        .with(Velocity { magnitude: dir.normalize() * 2000.0, last_change: 0.0, no_friction: true })
-                        .with(Lifespan { kill_at: time.seconds_since_startup + 0.5 });
-                }
+        if input_config.keyboard_aim {
+            continue;
+        }
+
+        let window = match windows.get(event.id) {
+            Some(window) => window,
+            None => continue,
+        };
+
+        for (_, camera_transform) in cameras.iter() {
+            let cursor_world = cursor_to_world(window, camera_transform, event.position);
+
+            for (t, mut shooter) in query.iter_mut() {
+                shooter.aim_angle = aim_angle_towards(t.translation.truncate(), cursor_world, shooter.muzzle_offset, &aim_origin);
             }
         }
     }
 }
 
-fn velocity_system(time: Res<Time>, mut query: Query<(&Velocity, &mut Transform)>) {
-    for (velocity, mut transform) in query.iter_mut() {
-        *transform.translation.x_mut() += velocity.magnitude.x() * time.delta_seconds;
-        *transform.translation.y_mut() += velocity.magnitude.y() * time.delta_seconds;
+fn keyboard_aim_system(time: Res<Time>, delta_clamp: Res<DeltaClamp>, input_config: Res<InputConfig>, keyboard_input: Res<Input<KeyCode>>, mut query: Query<&mut Shooter>) {
+    if !input_config.keyboard_aim {
+        return;
+    }
+
+    let mut dir = Vec2::zero();
+
+    if keyboard_input.pressed(KeyCode::Left) {
+        *dir.x_mut() -= 1.0;
+    }
+    if keyboard_input.pressed(KeyCode::Right) {
+        *dir.x_mut() += 1.0;
+    }
+    if keyboard_input.pressed(KeyCode::Up) {
+        *dir.y_mut() += 1.0;
+    }
+    if keyboard_input.pressed(KeyCode::Down) {
+        *dir.y_mut() -= 1.0;
+    }
+
+    if dir.length() > 0.0 {
+        let target = dir.y().atan2(dir.x());
+        let max_step = input_config.keyboard_turn_rate * delta_clamp.delta(&time);
+
+        for mut shooter in query.iter_mut() {
+            shooter.aim_angle = turn_towards(shooter.aim_angle, target, max_step);
+        }
     }
 }
 
-fn friction_system(time: Res<Time>, mut query: Query<(&mut Velocity)>) {
-    for mut velocity in query.iter_mut() {
-        if time.seconds_since_startup - velocity.last_change > 0.1 && !velocity.no_friction {
-            if velocity.magnitude.length() > 0.0 {
-                velocity.magnitude *= 0.7;
+fn aim_system(time: Res<Time>, delta_clamp: Res<DeltaClamp>, facing_mode: Res<FacingMode>, aim_mode: Res<AimMode>, aim_smoothing: Res<AimSmoothing>, mut query: Query<(&mut Transform, &mut Shooter)>) {
+    for (mut t, mut shooter) in query.iter_mut() {
+        shooter.smoothed_angle = if aim_smoothing.enabled {
+            turn_towards(shooter.smoothed_angle, shooter.aim_angle, aim_smoothing.turn_rate * delta_clamp.delta(&time))
+        } else {
+            shooter.aim_angle
+        };
+        shooter.shoot_angle = snap_angle(shooter.smoothed_angle, &aim_mode);
+        shooter.shoot_direction = Vec2::new(shooter.shoot_angle.cos(), shooter.shoot_angle.sin());
+
+        match *facing_mode {
+            FacingMode::Rotate => {
+                t.rotation = Quat::from_rotation_z(shooter.shoot_angle - PI / 2.0);
+                *t.scale.x_mut() = t.scale.x().abs();
+            }
+            FacingMode::Flip => {
+                t.rotation = Quat::identity();
+                *t.scale.x_mut() = t.scale.x().abs() * shooter.shoot_direction.x().signum();
             }
         }
     }
 }
 
-fn input_system(time: Res<Time>, keyboard_input: Res<Input<KeyCode>>, mut query: Query<(&mut Velocity, &Shooter)>) {
-    for (mut velocity, _) in query.iter_mut() {
-        let mut dir = Vec3::zero();
+fn fan_offset(index: u32, count: u32, spread: f32) -> f32 {
+    if count > 1 {
+        spread * (index as f32 / (count - 1) as f32 - 0.5)
+    } else {
+        0.0
+    }
+}
 
-        let accel = 5000.0;
-        let max_speed = 500.0;
+type SplittingParent<'a> = (&'a Splitting, &'a Transform, &'a Velocity, &'a Handle<ColorMaterial>, &'a Projectile, Option<&'a FaceVelocity>);
 
-        if keyboard_input.pressed(KeyCode::A) {
-            *dir.x_mut() -= 1.0 * time.delta_seconds;
+fn expire_projectile(commands: &mut Commands, delta_clamp: &DeltaClamp, despawn_queue: &mut DespawnQueue, splitting: &Query<SplittingParent>, entity: Entity) {
+    if !despawn_queue.push(entity) {
+        return;
+    }
+
+    let (splitting, transform, velocity, material, projectile, face_velocity) = match splitting.get(entity) {
+        Ok(parent) => parent,
+        Err(_) => return,
+    };
+
+    let speed = velocity.magnitude.length();
+    let heading = velocity.magnitude.y().atan2(velocity.magnitude.x());
+
+    for child in 0..splitting.count {
+        let angle = heading + fan_offset(child, splitting.count, splitting.spread);
+        let dir = Vec3::new(angle.cos(), angle.sin(), 0.0);
+
+        let mut child_transform = Transform::from_rotation(Quat::from_rotation_z(angle));
+        child_transform.translation = transform.translation;
+        child_transform.scale = transform.scale;
+
+        commands.spawn(SpriteComponents {
+            material: material.clone(),
+            transform: child_transform,
+            ..Default::default()
+        })
+            .with(Velocity { magnitude: dir * speed, last_change: 0.0, no_friction: true })
+            .with(Projectile { id: projectile.id })
+            .with(Lifespan { kill_at: delta_clamp.elapsed + splitting.lifespan });
+
+        if face_velocity.is_some() {
+            commands.with(FaceVelocity);
         }
-        if keyboard_input.pressed(KeyCode::D) {
-            *dir.x_mut() += 1.0 * time.delta_seconds;
+    }
+}
+
+fn kill_system(mut commands: Commands, delta_clamp: Res<DeltaClamp>, mut despawn_queue: ResMut<DespawnQueue>, query: Query<(Entity, &Lifespan)>, splitting: Query<SplittingParent>) {
+    for (entity, lifespan) in query.iter() {
+        if delta_clamp.elapsed >= lifespan.kill_at {
+            expire_projectile(&mut commands, &delta_clamp, &mut despawn_queue, &splitting, entity);
         }
-        if keyboard_input.pressed(KeyCode::W) {
-            *dir.y_mut() += 1.0 * time.delta_seconds;
+    }
+}
+
+fn range_system(mut commands: Commands, delta_clamp: Res<DeltaClamp>, mut despawn_queue: ResMut<DespawnQueue>, query: Query<(Entity, &Transform, &RangeLimit)>, splitting: Query<SplittingParent>) {
+    for (entity, transform, range) in query.iter() {
+        if (transform.translation - range.start).length() >= range.max_distance {
+            expire_projectile(&mut commands, &delta_clamp, &mut despawn_queue, &splitting, entity);
         }
-        if keyboard_input.pressed(KeyCode::S) {
-            *dir.y_mut() -= 1.0 * time.delta_seconds;
+    }
+}
+
+fn perf_governor_system(time: Res<Time>, delta_clamp: Res<DeltaClamp>, diagnostics: Res<Diagnostics>, mut governor: ResMut<PerfGovernor>) {
+    if let Some(frame_time) = diagnostics.get(FrameTimeDiagnosticsPlugin::FRAME_TIME).and_then(|diagnostic| diagnostic.average()) {
+        governor.observe(frame_time, delta_clamp.delta(&time));
+    }
+}
+
+fn projectile_cap_system(cap: Res<ProjectileCap>, governor: Res<PerfGovernor>, mut despawn_queue: ResMut<DespawnQueue>, query: Query<(Entity, &Projectile)>) {
+    let mut projectiles: Vec<(Entity, u64)> = query.iter()
+        .filter(|(entity, _)| !despawn_queue.contains(*entity))
+        .map(|(entity, projectile)| (entity, projectile.id))
+        .collect();
+
+    let max_live = (cap.max_live as f32 * governor.scale).ceil() as usize;
+
+    if projectiles.len() > max_live {
+        projectiles.sort_by_key(|(_, id)| *id);
+
+        for (entity, _) in projectiles.iter().take(projectiles.len() - max_live) {
+            despawn_queue.push(*entity);
         }
+    }
+}
 
-        if dir.length() > 0.0 {
-            dir = dir.normalize() * accel * time.delta_seconds;
+fn despawn_system(mut commands: Commands, mut despawn_queue: ResMut<DespawnQueue>) {
+    for entity in despawn_queue.pending.drain() {
+        commands.despawn(entity);
+    }
+}
+
+fn fire_mode_system(keyboard_input: Res<Input<KeyCode>>, mut fire_mode: ResMut<FireMode>) {
+    if keyboard_input.just_pressed(KeyCode::T) {
+        fire_mode.auto = !fire_mode.auto;
+    }
+}
 
-            *velocity.magnitude.x_mut() += dir.x();
-            *velocity.magnitude.y_mut() += dir.y();
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn fire_system(mut commands: Commands, delta_clamp: Res<DeltaClamp>, fire_mode: Res<FireMode>, aim_origin: Res<AimOrigin>, mut input_buffer: ResMut<InputBuffer>, mut rng: ResMut<GameRng>, weapon_database: Res<WeaponDatabase>, mouse_input: Res<Input<MouseButton>>, keyboard_input: Res<Input<KeyCode>>, mut next_projectile_id: Local<u64>, mut query: Query<(&Transform, &Velocity, &mut Shooter, Option<&mut Heat>, Option<&mut Bloom>)>) {
+    if mouse_input.just_pressed(MouseButton::Left) || keyboard_input.just_pressed(KeyCode::Space) {
+        input_buffer.fire_pressed_at = Some(delta_clamp.elapsed);
+    }
 
-            if velocity.magnitude.length() > max_speed {
-                velocity.magnitude = velocity.magnitude.normalize() * max_speed;
-            }
+    if let Some(pressed_at) = input_buffer.fire_pressed_at {
+        if delta_clamp.elapsed - pressed_at > input_buffer.window {
+            input_buffer.fire_pressed_at = None;
+        }
+    }
+
+    let held = mouse_input.pressed(MouseButton::Left) || keyboard_input.pressed(KeyCode::Space);
+    let firing = (fire_mode.auto && held) || input_buffer.fire_pressed_at.is_some();
+
+    if !firing {
+        return;
+    }
+
+    for (t, shooter_velocity, mut shooter, heat, bloom) in query.iter_mut() {
+        let stats = match weapon_database.weapons.get(&shooter.weapon) {
+            Some(stats) => stats,
+            None => continue,
+        };
 
-            velocity.last_change = time.seconds_since_startup
+        if let Some(heat) = &heat {
+            if heat.locked {
+                continue;
+            }
         }
+
+        if delta_clamp.elapsed - shooter.last_shot_at > stats.cooldown {
+            shooter.last_shot_at = delta_clamp.elapsed;
+            input_buffer.fire_pressed_at = None;
+
+            if let Some(mut heat) = heat {
+                heat.current += heat.config.per_shot;
+
+                if heat.current >= heat.config.max {
+                    heat.current = heat.config.max;
+                    heat.locked = true;
+                }
+            }
+
+            let muzzle = muzzle_position(t.translation, shooter.shoot_angle, shooter.muzzle_offset, &aim_origin);
+            let bloom_spread = bloom.as_ref().map(|bloom| bloom.current).unwrap_or_default();
+
+            if let Some(mut bloom) = bloom {
+                bloom.current = (bloom.current + bloom.config.per_shot).min(bloom.config.max);
+            }
+
+            for pellet in 0..stats.pellets {
+                let jitter = if bloom_spread > 0.0 { rng.0.gen_range(-bloom_spread / 2.0, bloom_spread / 2.0) } else { 0.0 };
+                let angle = shooter.shoot_angle + fan_offset(pellet, stats.pellets, stats.spread) + jitter;
+                let dir = Vec3::new(angle.cos(), angle.sin(), 0.0);
+
+                let mut transform = Transform::from_rotation(Quat::from_rotation_z(angle));
+                transform.translation = muzzle;
+                transform.scale = Vec3::splat(stats.scale);
+
+                commands.spawn(SpriteComponents {
+                    material: shooter.pew_handle.clone(),
+                    transform,
+                    ..Default::default()
+                })
+                    .with(Velocity { magnitude: dir * stats.projectile_speed + shooter_velocity.magnitude * stats.inherit_velocity, last_change: 0.0, no_friction: true })
+                    .with(Projectile { id: *next_projectile_id });
+
+                *next_projectile_id += 1;
+
+                if let Some(lifespan) = stats.lifespan {
+                    commands.with(Lifespan { kill_at: delta_clamp.elapsed + lifespan });
+                }
+
+                if let Some(max_distance) = stats.range {
+                    commands.with(RangeLimit { max_distance, start: transform.translation });
+                }
+
+                if !stats.symmetric {
+                    commands.with(FaceVelocity);
+                }
+
+                if let Some(splitting) = &stats.split {
+                    commands.with(splitting.clone());
+                }
+            }
+        }
+    }
+}
+
+fn heat_system(time: Res<Time>, delta_clamp: Res<DeltaClamp>, weapon_database: Res<WeaponDatabase>, mut query: Query<(&Shooter, &mut Heat)>) {
+    let delta = delta_clamp.delta(&time);
+
+    for (shooter, mut heat) in query.iter_mut() {
+        let cooldown = weapon_database.weapons.get(&shooter.weapon).map(|stats| stats.cooldown).unwrap_or_default();
+
+        if delta_clamp.elapsed - shooter.last_shot_at > cooldown {
+            heat.current = (heat.current - heat.config.cooldown_rate * delta).max(0.0);
+        }
+
+        if heat.locked && heat.current <= heat.config.reset_below {
+            heat.locked = false;
+        }
+    }
+}
+
+fn bloom_system(time: Res<Time>, delta_clamp: Res<DeltaClamp>, mut query: Query<&mut Bloom>) {
+    let delta = delta_clamp.delta(&time);
+
+    for mut bloom in query.iter_mut() {
+        bloom.current = (bloom.current - bloom.config.recovery * delta).max(0.0);
+    }
+}
+
+fn delta_clamp_system(time: Res<Time>, mut delta_clamp: ResMut<DeltaClamp>) {
+    delta_clamp.elapsed += delta_clamp.delta(&time) as f64;
+}
+
+fn velocity_system(time: Res<Time>, delta_clamp: Res<DeltaClamp>, mut query: Query<(&Velocity, &mut Transform)>) {
+    let delta = delta_clamp.delta(&time);
+
+    for (velocity, mut transform) in query.iter_mut() {
+        *transform.translation.x_mut() += velocity.magnitude.x() * delta;
+        *transform.translation.y_mut() += velocity.magnitude.y() * delta;
+    }
+}
+
+fn face_velocity_system(mut query: Query<(&FaceVelocity, &Velocity, &mut Transform)>) {
+    for (_, velocity, mut transform) in query.iter_mut() {
+        if velocity.magnitude.length() > 0.0 {
+            transform.rotation = Quat::from_rotation_z(velocity.magnitude.y().atan2(velocity.magnitude.x()));
+        }
+    }
+}
+
+fn apply_mutators_system(mutators: Res<Mutators>, mut movement: ResMut<MovementConfig>) {
+    if mutators.ice_physics {
+        movement.velocity_retention = 0.98;
+        movement.responsiveness = 0.05;
+    }
+}
+
+fn friction_system(time: Res<Time>, delta_clamp: Res<DeltaClamp>, movement: Res<MovementConfig>, mut query: Query<&mut Velocity>) {
+    let retention = movement.velocity_retention.powf(delta_clamp.delta(&time) * 60.0);
+
+    for mut velocity in query.iter_mut() {
+        if delta_clamp.elapsed - velocity.last_change > 0.1 && !velocity.no_friction && velocity.magnitude.length() > 0.0 {
+            velocity.magnitude *= retention;
+        }
+    }
+}
+
+fn input_system(time: Res<Time>, delta_clamp: Res<DeltaClamp>, movement: Res<MovementConfig>, keyboard_input: Res<Input<KeyCode>>, mut query: Query<(&mut Velocity, &Shooter)>) {
+    let delta = delta_clamp.delta(&time);
+
+    for (mut velocity, _) in query.iter_mut() {
+        let mut dir = Vec3::zero();
+
+        if keyboard_input.pressed(KeyCode::A) {
+            *dir.x_mut() -= 1.0;
+        }
+        if keyboard_input.pressed(KeyCode::D) {
+            *dir.x_mut() += 1.0;
+        }
+        if keyboard_input.pressed(KeyCode::W) {
+            *dir.y_mut() += 1.0;
+        }
+        if keyboard_input.pressed(KeyCode::S) {
+            *dir.y_mut() -= 1.0;
+        }
+
+        if dir.length() > 0.0 {
+            let desired = dir.normalize() * movement.max_speed;
+            let blend = 1.0 - (1.0 - movement.responsiveness.clamp(0.01, 1.0)).powf(delta * 60.0);
+
+            velocity.magnitude = velocity.magnitude.lerp(desired, blend);
+
+            if velocity.magnitude.length() > movement.max_speed {
+                velocity.magnitude = velocity.magnitude.normalize() * movement.max_speed;
+            }
+
+            velocity.last_change = delta_clamp.elapsed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::input::keyboard::KeyboardInput;
+    use bevy::window::WindowId;
+
+    use super::*;
+
+    fn press_mouse(app: &mut App, button: MouseButton) {
+        app.resources.get_mut::<Events<MouseButtonInput>>().unwrap().send(MouseButtonInput { button, state: ElementState::Pressed });
+    }
+
+    fn release_mouse(app: &mut App, button: MouseButton) {
+        app.resources.get_mut::<Events<MouseButtonInput>>().unwrap().send(MouseButtonInput { button, state: ElementState::Released });
+    }
+
+    fn press_key(app: &mut App, key_code: KeyCode) {
+        app.resources.get_mut::<Events<KeyboardInput>>().unwrap().send(KeyboardInput { scan_code: 0, key_code: Some(key_code), state: ElementState::Pressed });
+    }
+
+    fn click_every(app: &mut App, tick: u32, interval: u32) {
+        if tick == 0 {
+            app.resources.get_mut::<FireMode>().unwrap().auto = false;
+        }
+
+        match tick % interval {
+            0 => press_mouse(app, MouseButton::Left),
+            1 => release_mouse(app, MouseButton::Left),
+            _ => {}
+        }
+    }
+
+    fn projectile_ids(app: &mut App) -> Vec<u64> {
+        let mut ids: Vec<u64> = app.world.query::<&Projectile>().map(|projectile| projectile.id).collect();
+        ids.sort_unstable();
+
+        ids
+    }
+
+    fn player_shooter(app: &mut App) -> (f64, f32) {
+        app.world.query::<&Shooter>().map(|shooter| (shooter.last_shot_at, shooter.shoot_angle)).next().unwrap()
+    }
+
+    fn bundled_weapons() -> WeaponDatabase {
+        parse_weapon_database(include_str!("../assets/weapons.ron")).unwrap()
+    }
+
+    fn pistol() -> WeaponStats {
+        WeaponDatabase::default().weapons.remove("pistol").unwrap()
+    }
+
+    fn single_weapon(stats: WeaponStats) -> WeaponDatabase {
+        let mut weapons = HashMap::new();
+        weapons.insert(Loadout::default().weapon, stats);
+
+        WeaponDatabase { weapons }
+    }
+
+    #[test]
+    fn headless_run_survives_600_ticks() {
+        let report = run_headless(600, 0, Mutators::default(), Loadout::default(), WeaponDatabase::default(), |_, _| {});
+
+        assert_eq!(report.ticks, 600);
+        assert_eq!(report.entities, 2);
+        assert_eq!(report.projectiles, 0);
+        assert_eq!(report.player_position, Vec2::zero());
+    }
+
+    #[test]
+    fn headless_run_survives_600_ticks_of_fire() {
+        let report = run_headless(600, 0, Mutators::default(), Loadout::default(), WeaponDatabase::default(), |tick, app| {
+            if tick == 0 {
+                press_mouse(app, MouseButton::Left);
+            }
+        });
+
+        assert_eq!(report.ticks, 600);
+        assert!(report.projectiles > 0);
+        assert_eq!(report.entities, 2 + report.projectiles);
+    }
+
+    #[test]
+    fn holding_fire_repeats_at_the_cooldown() {
+        let report = run_headless(21, 0, Mutators::default(), Loadout::default(), WeaponDatabase::default(), |tick, app| {
+            if tick == 0 {
+                press_mouse(app, MouseButton::Left);
+            }
+        });
+
+        assert!(report.projectiles >= 3 && report.projectiles <= 4, "fired {} shots in 0.35s", report.projectiles);
+    }
+
+    #[test]
+    fn parses_a_sample_weapon_file() {
+        let database = parse_weapon_database(r#"(
+            weapons: {
+                "blaster": (
+                    cooldown: 0.25,
+                    projectile_speed: 1500.0,
+                    lifespan: Some(0.75),
+                    spread: 0.2,
+                    pellets: 3,
+                    scale: 1.5,
+                ),
+            },
+        )"#).unwrap();
+
+        let blaster = &database.weapons["blaster"];
+
+        assert_eq!(blaster.cooldown, 0.25);
+        assert_eq!(blaster.projectile_speed, 1500.0);
+        assert_eq!(blaster.lifespan, Some(0.75));
+        assert_eq!(blaster.range, None);
+        assert_eq!(blaster.spread, 0.2);
+        assert_eq!(blaster.pellets, 3);
+        assert_eq!(blaster.scale, 1.5);
+        assert_eq!(blaster.inherit_velocity, 0.0);
+        assert!(blaster.split.is_none() && blaster.heat.is_none() && blaster.bloom.is_none());
+    }
+
+    #[test]
+    fn malformed_weapon_file_is_an_error() {
+        assert!(parse_weapon_database("(weapons: { \"blaster\": (cooldown: \"fast\") })").is_err());
+    }
+
+    #[test]
+    fn bundled_weapon_file_parses() {
+        let database = bundled_weapons();
+
+        for weapon in &["pistol", "shotgun", "cluster", "laser"] {
+            assert!(database.weapons.contains_key(*weapon), "missing {}", weapon);
+        }
+    }
+
+    #[test]
+    fn weapon_without_lifespan_or_range_is_rejected() {
+        let error = parse_weapon_database("(weapons: { \"blaster\": (cooldown: 0.1, projectile_speed: 1000.0, spread: 0.0, pellets: 1) })").err().unwrap();
+
+        assert!(error.contains("blaster"));
+    }
+
+    fn despawn_distance(projectile_speed: f32) -> f32 {
+        let stats = WeaponStats { projectile_speed, lifespan: None, range: Some(500.0), ..pistol() };
+        let mut last_distance = None;
+
+        run_headless(60, 0, Mutators::default(), Loadout::default(), single_weapon(stats), |tick, app| {
+            if tick == 0 {
+                press_mouse(app, MouseButton::Left);
+            }
+
+            for (projectile, transform, range) in app.world.query::<(&Projectile, &Transform, &RangeLimit)>() {
+                if projectile.id == 0 {
+                    last_distance = Some((transform.translation - range.start).length());
+                }
+            }
+        });
+
+        last_distance.unwrap()
+    }
+
+    #[test]
+    fn slow_and_fast_projectiles_despawn_at_the_same_range() {
+        let slow = despawn_distance(1000.0);
+        let fast = despawn_distance(2000.0);
+
+        assert!(slow < 500.0 && 500.0 - slow <= 1000.0 / 60.0 + 0.1, "slow projectile last seen at {}", slow);
+        assert!(fast < 500.0 && 500.0 - fast <= 2000.0 / 60.0 + 0.1, "fast projectile last seen at {}", fast);
+    }
+
+    #[test]
+    fn weapon_arg_selects_the_loadout() {
+        let args: Vec<String> = vec!["test1".into(), "--weapon".into(), "shotgun".into()];
+
+        assert_eq!(Loadout::from_args(&args).weapon, "shotgun");
+        assert_eq!(Loadout::from_args(&args[..1]).weapon, "pistol");
+    }
+
+    #[test]
+    fn selected_weapon_is_equipped() {
+        let database = bundled_weapons();
+        let report = run_headless(40, 0, Mutators::default(), Loadout { weapon: "shotgun".to_string() }, database, |tick, app| {
+            if tick == 0 {
+                press_mouse(app, MouseButton::Left);
+            }
+        });
+
+        assert_eq!(report.projectiles, 6);
+    }
+
+    #[test]
+    fn empty_weapon_file_is_rejected() {
+        assert!(parse_weapon_database("(weapons: {})").is_err());
+    }
+
+    #[test]
+    fn unknown_weapon_falls_back_to_a_defined_one() {
+        let database = parse_weapon_database(r#"(
+            weapons: {
+                "blaster": (cooldown: 0.25, projectile_speed: 1500.0, lifespan: Some(0.75), spread: 0.0, pellets: 2),
+            },
+        )"#).unwrap();
+
+        assert_eq!(database.resolve("railgun"), "blaster");
+
+        let report = run_headless(20, 0, Mutators::default(), Loadout { weapon: "railgun".to_string() }, database, |tick, app| {
+            if tick == 0 {
+                press_mouse(app, MouseButton::Left);
+            }
+        });
+
+        assert_eq!(report.projectiles, 2);
+    }
+
+    #[test]
+    fn eight_way_aim_snaps_to_the_nearest_direction() {
+        let angle = 80.0_f32.to_radians();
+
+        assert!((snap_angle(angle, &AimMode::EightWay) - PI / 2.0).abs() < 1e-6);
+        assert_eq!(snap_angle(angle, &AimMode::Free), angle);
+    }
+
+    #[test]
+    fn projectile_cap_evicts_the_oldest() {
+        let stats = WeaponStats { lifespan: Some(10.0), ..pistol() };
+        let mut ids = Vec::new();
+
+        run_headless(81, 0, Mutators::default(), Loadout::default(), single_weapon(stats), |tick, app| {
+            if tick == 0 {
+                app.resources.get_mut::<ProjectileCap>().unwrap().max_live = 3;
+            }
+
+            if tick < 80 {
+                click_every(app, tick, 10);
+            }
+
+            ids = projectile_ids(app);
+        });
+
+        assert_eq!(ids, vec![5, 6, 7]);
+    }
+
+    #[test]
+    fn buffered_press_fires_when_the_cooldown_ends() {
+        let stats = WeaponStats { cooldown: 0.3, lifespan: Some(10.0), ..pistol() };
+        let mut shots = Vec::new();
+
+        run_headless(50, 0, Mutators::default(), Loadout::default(), single_weapon(stats), |tick, app| {
+            if tick == 0 {
+                app.resources.get_mut::<FireMode>().unwrap().auto = false;
+            }
+
+            match tick {
+                20 | 32 => press_mouse(app, MouseButton::Left),
+                21 | 33 => release_mouse(app, MouseButton::Left),
+                _ => {}
+            }
+
+            let (last_shot_at, _) = player_shooter(app);
+
+            if projectile_ids(app).len() > shots.len() {
+                shots.push(last_shot_at);
+            }
+        });
+
+        assert_eq!(shots.len(), 2);
+
+        let second_press_at = 33.0 / 60.0;
+        let cooldown_ends_at = shots[0] + 0.3;
+
+        assert!(cooldown_ends_at - second_press_at > 0.09);
+        assert!(shots[1] > cooldown_ends_at && shots[1] - cooldown_ends_at <= 1.0 / 60.0 + 1e-6, "buffered shot at {}, cooldown ended at {}", shots[1], cooldown_ends_at);
+    }
+
+    #[test]
+    fn smoothed_aim_lags_behind_a_flick() {
+        let mut angles = Vec::new();
+
+        run_headless(60, 0, Mutators::default(), Loadout::default(), WeaponDatabase::default(), |tick, app| {
+            if tick == 0 {
+                app.resources.get_mut::<AimSmoothing>().unwrap().enabled = true;
+
+                for mut shooter in app.world.query_mut::<&mut Shooter>() {
+                    shooter.aim_angle = PI;
+                }
+            }
+
+            angles.push(player_shooter(app).1);
+        });
+
+        let settled = angles.iter().position(|angle| (angle.abs() - PI).abs() < 1e-6);
+
+        assert!(settled.is_some(), "a 180 degree flick never settled, ended at {}", angles[angles.len() - 1]);
+        assert!(settled.unwrap() >= 10, "a 180 degree flick settled in {} frames", settled.unwrap());
+        assert!(angles[5].abs() > 0.0 && angles[5].abs() < PI, "turned to {} after five frames", angles[5]);
+    }
+
+    #[test]
+    fn smoothed_eight_way_aim_stays_snapped() {
+        let mut angles = Vec::new();
+
+        run_headless(30, 0, Mutators::default(), Loadout::default(), WeaponDatabase::default(), |tick, app| {
+            if tick == 0 {
+                *app.resources.get_mut::<AimMode>().unwrap() = AimMode::EightWay;
+                app.resources.get_mut::<AimSmoothing>().unwrap().enabled = true;
+
+                for mut shooter in app.world.query_mut::<&mut Shooter>() {
+                    shooter.aim_angle = 2.0;
+                }
+            }
+
+            angles.push(player_shooter(app).1);
+        });
+
+        assert!((angles.last().unwrap() - PI * 3.0 / 4.0).abs() < 1e-6);
+
+        for angle in angles {
+            let steps = angle / (PI / 4.0);
+            assert!((steps - steps.round()).abs() < 1e-6, "fired along {}", angle);
+        }
+    }
+
+    #[test]
+    fn expiring_splitter_fans_out_children() {
+        let stats = WeaponStats { lifespan: Some(0.35), split: Some(Splitting { count: 5, spread: 1.2, lifespan: 0.25 }), ..pistol() };
+        let mut children = Vec::new();
+
+        run_headless(35, 0, Mutators::default(), Loadout::default(), single_weapon(stats), |tick, app| {
+            match tick {
+                0 => press_mouse(app, MouseButton::Left),
+                1 => release_mouse(app, MouseButton::Left),
+                _ => {}
+            }
+
+            children = app.world.query::<(&Projectile, &Velocity, Option<&Splitting>, Option<&FaceVelocity>)>()
+                .map(|(projectile, velocity, splitting, face_velocity)| (projectile.id, velocity.magnitude.y().atan2(velocity.magnitude.x()), splitting.is_some(), face_velocity.is_some()))
+                .collect();
+        });
+
+        children.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        assert_eq!(children.len(), 5);
+
+        for ((id, angle, splits, faces_velocity), expected) in children.into_iter().zip(&[-0.6, -0.3, 0.0, 0.3, 0.6]) {
+            assert_eq!(id, 0);
+            assert!((angle - expected).abs() < 1e-4, "child heading {} expected {}", angle, expected);
+            assert!(!splits);
+            assert!(faces_velocity);
+        }
+    }
+
+    #[test]
+    fn long_frames_are_clamped() {
+        let mut mover = None;
+        let mut positions = Vec::new();
+
+        run_headless(3, 0, Mutators::default(), Loadout::default(), WeaponDatabase::default(), |tick, app| {
+            if tick == 0 {
+                mover = Some(app.world.spawn((Velocity { magnitude: Vec3::new(100.0, 0.0, 0.0), last_change: 0.0, no_friction: true }, Transform::default())));
+            }
+
+            if tick == 1 {
+                let mut time = app.resources.get_mut::<Time>().unwrap();
+                time.delta_seconds = 2.0;
+                time.delta_seconds_f64 = 2.0;
+                time.seconds_since_startup += 2.0;
+            }
+
+            positions.push(app.world.get::<Transform>(mover.unwrap()).unwrap().translation.x());
+        });
+
+        assert!((positions[2] - positions[1] - 10.0).abs() < 1e-3, "a 2s frame moved {}", positions[2] - positions[1]);
+    }
+
+    #[test]
+    fn long_frames_do_not_expire_projectiles_early() {
+        let mut live = Vec::new();
+
+        run_headless(14, 0, Mutators::default(), Loadout::default(), WeaponDatabase::default(), |tick, app| {
+            if tick == 0 {
+                press_mouse(app, MouseButton::Left);
+            }
+
+            if tick == 10 {
+                release_mouse(app, MouseButton::Left);
+            }
+
+            if tick == 12 {
+                let mut time = app.resources.get_mut::<Time>().unwrap();
+                time.delta_seconds = 2.0;
+                time.delta_seconds_f64 = 2.0;
+                time.seconds_since_startup += 2.0;
+            }
+
+            live.push(app.world.query::<&Projectile>().count());
+        });
+
+        assert!(live[12] > 0, "nothing was fired");
+        assert_eq!(live[13], live[12], "a 2s frame expired {} projectiles", live[12] - live[13]);
+    }
+
+    #[test]
+    fn overheated_weapon_locks_until_cooled() {
+        let mut samples = Vec::new();
+
+        run_headless(240, 0, Mutators::default(), Loadout { weapon: "laser".to_string() }, bundled_weapons(), |tick, app| {
+            if tick == 0 {
+                press_mouse(app, MouseButton::Left);
+            }
+
+            let (last_shot_at, _) = player_shooter(app);
+            let heat = app.world.query::<&Heat>().map(|heat| (heat.current, heat.locked)).next().unwrap();
+
+            samples.push((last_shot_at, heat.0, heat.1));
+        });
+
+        let locked_at = samples.iter().position(|(_, _, locked)| *locked).expect("never overheated");
+        let unlocked_at = locked_at + samples[locked_at..].iter().position(|(_, _, locked)| !*locked).expect("never cooled down");
+
+        assert_eq!(samples[locked_at].1, 100.0);
+        assert!(samples[unlocked_at].1 <= 30.0);
+        assert!(samples[unlocked_at - 1].1 > 30.0);
+
+        for (last_shot_at, _, _) in &samples[locked_at..unlocked_at] {
+            assert_eq!(*last_shot_at, samples[locked_at].0);
+        }
+
+        assert!(samples.last().unwrap().0 > samples[locked_at].0, "firing never resumed");
+    }
+
+    #[test]
+    fn rotation_follows_a_turning_velocity() {
+        let mut mover = None;
+        let mut rotations = Vec::new();
+
+        run_headless(4, 0, Mutators::default(), Loadout::default(), WeaponDatabase::default(), |tick, app| {
+            match tick {
+                0 => mover = Some(app.world.spawn((FaceVelocity, Velocity { magnitude: Vec3::new(100.0, 0.0, 0.0), last_change: 0.0, no_friction: true }, Transform::default()))),
+                2 => app.world.get_mut::<Velocity>(mover.unwrap()).unwrap().magnitude = Vec3::new(0.0, 100.0, 0.0),
+                _ => {}
+            }
+
+            rotations.push(app.world.get::<Transform>(mover.unwrap()).unwrap().rotation);
+        });
+
+        assert!(rotations[2].abs_diff_eq(Quat::identity(), 1e-5));
+        assert!(rotations[3].abs_diff_eq(Quat::from_rotation_z(PI / 2.0), 1e-5));
+    }
+
+    #[test]
+    fn weapon_scale_applies_to_projectiles() {
+        let stats = WeaponStats { scale: 2.0, ..pistol() };
+        let mut scales = Vec::new();
+
+        run_headless(10, 0, Mutators::default(), Loadout::default(), single_weapon(stats), |tick, app| {
+            if tick == 0 {
+                press_mouse(app, MouseButton::Left);
+            }
+
+            scales = app.world.query::<(&Projectile, &Transform)>().map(|(_, transform)| transform.scale).collect();
+        });
+
+        assert_eq!(scales, vec![Vec3::splat(2.0)]);
+    }
+
+    fn velocity_after_turning_up(responsiveness: f32) -> Vec3 {
+        let mut velocities = Vec::new();
+
+        run_headless(2, 0, Mutators::default(), Loadout::default(), WeaponDatabase::default(), |tick, app| {
+            if tick == 0 {
+                app.resources.get_mut::<MovementConfig>().unwrap().responsiveness = responsiveness;
+                press_key(app, KeyCode::W);
+
+                for (mut velocity, _) in app.world.query_mut::<(&mut Velocity, &Shooter)>() {
+                    velocity.magnitude = Vec3::new(500.0, 0.0, 0.0);
+                }
+            }
+
+            velocities.extend(app.world.query::<(&Velocity, &Shooter)>().map(|(velocity, _)| velocity.magnitude));
+        });
+
+        velocities[1]
+    }
+
+    #[test]
+    fn full_responsiveness_turns_in_one_frame() {
+        assert!(velocity_after_turning_up(1.0).abs_diff_eq(Vec3::new(0.0, 500.0, 0.0), 1e-3));
+    }
+
+    #[test]
+    fn low_responsiveness_keeps_most_of_the_old_velocity() {
+        let velocity = velocity_after_turning_up(0.1);
+
+        assert!(velocity.x() > 400.0 && velocity.y() < 100.0, "turned to {:?}", velocity);
+    }
+
+    #[test]
+    fn zero_responsiveness_still_turns_towards_the_input() {
+        let velocity = velocity_after_turning_up(0.0);
+
+        assert!(velocity.x() < 500.0 && velocity.y() > 0.0, "turned to {:?}", velocity);
+    }
+
+    fn miss_distance(origin: Vec2, target: Vec2, aim_origin: &AimOrigin) -> f32 {
+        let muzzle_offset = Vec2::new(50.0, -8.0);
+        let angle = aim_angle_towards(origin, target, muzzle_offset, aim_origin);
+        let spawn = muzzle_position(origin.extend(0.0), angle, muzzle_offset, aim_origin).truncate();
+        let forward = Vec2::new(angle.cos(), angle.sin());
+        let to_target = target - spawn;
+
+        assert!(to_target.dot(forward) > 0.0, "{:?} is behind the spawn point", target);
+
+        (to_target - forward * to_target.dot(forward)).length()
+    }
+
+    #[test]
+    fn muzzle_aim_passes_through_a_cursor_beside_the_player() {
+        assert!(miss_distance(Vec2::new(10.0, 20.0), Vec2::new(10.0, 100.0), &AimOrigin::Muzzle) < 1e-3);
+        assert!(miss_distance(Vec2::new(10.0, 20.0), Vec2::new(-60.0, 20.0), &AimOrigin::Muzzle) < 1e-3);
+    }
+
+    #[test]
+    fn center_aim_passes_through_the_cursor() {
+        assert!(miss_distance(Vec2::new(10.0, 20.0), Vec2::new(300.0, 140.0), &AimOrigin::Center) < 1e-3);
+    }
+
+    #[test]
+    fn cursor_inside_the_muzzle_aims_from_the_center() {
+        let angle = aim_angle_towards(Vec2::zero(), Vec2::new(0.0, 30.0), Vec2::new(50.0, -8.0), &AimOrigin::Muzzle);
+
+        assert!((angle - PI / 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn projectiles_inherit_the_shooter_velocity() {
+        let stats = WeaponStats { inherit_velocity: 1.0, ..pistol() };
+        let mut velocities = Vec::new();
+
+        run_headless(10, 0, Mutators::default(), Loadout::default(), single_weapon(stats), |tick, app| {
+            if tick == 0 {
+                app.resources.get_mut::<MovementConfig>().unwrap().responsiveness = 1.0;
+                press_key(app, KeyCode::D);
+                press_mouse(app, MouseButton::Left);
+            }
+
+            velocities = app.world.query::<(&Projectile, &Velocity)>().map(|(_, velocity)| velocity.magnitude).collect();
+        });
+
+        assert_eq!(velocities.len(), 1);
+        assert!(velocities[0].abs_diff_eq(Vec3::new(2500.0, 0.0, 0.0), 1e-2), "projectile velocity {:?}", velocities[0]);
+    }
+
+    #[test]
+    fn bloom_rises_under_fire_and_recovers() {
+        let mut blooms = Vec::new();
+
+        run_headless(180, 0, Mutators::default(), Loadout::default(), bundled_weapons(), |tick, app| {
+            match tick {
+                0 => press_mouse(app, MouseButton::Left),
+                60 => release_mouse(app, MouseButton::Left),
+                _ => {}
+            }
+
+            blooms.extend(app.world.query::<&Bloom>().map(|bloom| bloom.current));
+        });
+
+        assert!(blooms[60] > blooms[20] && blooms[20] > 0.0, "bloom went {} -> {}", blooms[20], blooms[60]);
+        assert_eq!(*blooms.last().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn queueing_an_entity_twice_despawns_it_once() {
+        let mut target = None;
+        let mut pushes = Vec::new();
+        let mut alive = Vec::new();
+
+        run_headless(3, 0, Mutators::default(), Loadout::default(), WeaponDatabase::default(), |tick, app| {
+            if tick == 0 {
+                target = Some(app.world.spawn((Transform::default(),)));
+            }
+
+            if tick == 1 {
+                let mut despawn_queue = app.resources.get_mut::<DespawnQueue>().unwrap();
+                pushes.push(despawn_queue.push(target.unwrap()));
+                pushes.push(despawn_queue.push(target.unwrap()));
+            }
+
+            alive.push(app.world.contains(target.unwrap()));
+        });
+
+        assert_eq!(pushes, vec![true, false]);
+        assert_eq!(alive, vec![true, true, false]);
+    }
+
+    #[test]
+    fn keyboard_aim_turns_and_fires_upwards() {
+        let mut shoot_angles = Vec::new();
+        let mut newest = None;
+
+        run_headless(40, 0, Mutators::default(), Loadout::default(), WeaponDatabase::default(), |tick, app| {
+            if tick == 0 {
+                app.resources.get_mut::<InputConfig>().unwrap().keyboard_aim = true;
+                press_key(app, KeyCode::Up);
+                press_mouse(app, MouseButton::Left);
+            }
+
+            shoot_angles.push(player_shooter(app).1);
+            newest = app.world.query::<(&Projectile, &Velocity)>().max_by_key(|(projectile, _)| projectile.id).map(|(_, velocity)| velocity.magnitude.normalize());
+        });
+
+        assert!(shoot_angles[5] > 0.0 && shoot_angles[5] < PI / 2.0, "turned to {} after five frames", shoot_angles[5]);
+        assert!((shoot_angles[shoot_angles.len() - 1] - PI / 2.0).abs() < 1e-6);
+        assert!(newest.unwrap().abs_diff_eq(Vec3::new(0.0, 1.0, 0.0), 1e-5), "fired along {:?}", newest);
+    }
+
+    #[test]
+    fn slow_frames_lower_the_governor_scale() {
+        let mut governor = PerfGovernor { degrade_above: 1.0 / 30.0, recover_below: 1.0 / 50.0, adjust_rate: 0.5, min_scale: 0.25, scale: 1.0 };
+
+        governor.observe(0.05, 0.1);
+        assert!((governor.scale - 0.95).abs() < 1e-6);
+
+        for _ in 0..100 {
+            governor.observe(0.05, 0.1);
+        }
+        assert_eq!(governor.scale, 0.25);
+
+        governor.observe(1.0 / 60.0, 0.1);
+        assert!((governor.scale - 0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ice_physics_raises_velocity_retention_at_run_start() {
+        let mut retentions = Vec::new();
+
+        run_headless(1, 0, Mutators { ice_physics: true }, Loadout::default(), WeaponDatabase::default(), |_, app| {
+            retentions.push(app.resources.get::<MovementConfig>().unwrap().velocity_retention);
+        });
+
+        assert_eq!(retentions, vec![0.98]);
+    }
+
+    #[test]
+    fn game_plugin_defaults_missing_mutators() {
+        let mut builder = App::build();
+        builder.add_plugin(GamePlugin);
+
+        assert!(!builder.resources().get::<Mutators>().unwrap().ice_physics);
+        assert!(builder.resources().contains::<GameRng>());
+    }
+
+    #[test]
+    fn game_plugin_keeps_a_seeded_rng() {
+        let mut builder = App::build();
+        builder.add_resource(GameRng(StdRng::seed_from_u64(7))).add_plugin(GamePlugin);
+
+        let rolled: u64 = builder.resources().get_mut::<GameRng>().unwrap().0.gen();
+
+        assert_eq!(rolled, StdRng::seed_from_u64(7).gen::<u64>());
+    }
+
+    #[test]
+    fn centered_cursor_maps_to_the_camera() {
+        let window = Window::new(WindowId::new(), &WindowDescriptor::default());
+        let camera = Transform::from_translation(Vec3::new(100.0, -50.0, 0.0));
+
+        assert_eq!(cursor_to_world(&window, &camera, Vec2::new(640.0, 360.0)), Vec2::new(100.0, -50.0));
+    }
+
+    #[test]
+    fn off_center_cursor_is_offset_by_the_camera_scale() {
+        let window = Window::new(WindowId::new(), &WindowDescriptor::default());
+        let mut camera = Transform::from_translation(Vec3::new(100.0, -50.0, 0.0));
+        camera.scale = Vec3::splat(2.0);
+
+        assert_eq!(cursor_to_world(&window, &camera, Vec2::new(740.0, 260.0)), Vec2::new(300.0, -250.0));
     }
 }